@@ -1,12 +1,30 @@
-use crate::models::{OidcIdentity, SessionStore};
+use crate::models::{OidcIdentity, RefreshTokenRecord, SessionStore};
 use chrono::Utc;
 use cloudflare::framework::{
     async_api::Client as CloudflareClient,
     auth::Credentials,
     Environment, HttpApiClientConfig,
 };
+use async_trait::async_trait;
+use serde::Deserialize;
 use serde_json;
 use std::collections::HashMap;
+use std::sync::Arc;
+use tower_sessions::session::{Id as TowerSessionId, Record as TowerSessionRecord};
+use tower_sessions::session_store::{Error as TowerSessionStoreError, Result as TowerSessionStoreResult};
+
+/// Cloudflare's KV REST API is not exposed by the `cloudflare` crate's typed endpoints
+/// for per-key writes with an expiration, so session storage talks to it directly.
+const CLOUDFLARE_API_BASE: &str = "https://api.cloudflare.com/client/v4";
+
+const SESSION_KEY_PREFIX: &str = "session:";
+
+/// Key prefix for `tower-sessions` records, kept distinct from our own
+/// `session:{id}` `OidcIdentity` entries above since they serialize a different shape.
+const TOWER_SESSION_KEY_PREFIX: &str = "tss:";
+
+/// Key prefix for `RefreshTokenRecord` entries, one per session.
+const REFRESH_TOKEN_KEY_PREFIX: &str = "refresh:";
 
 #[derive(Debug, thiserror::Error)]
 pub enum CloudflareError {
@@ -20,16 +38,51 @@ pub enum CloudflareError {
     AuthError(String),
 }
 
+#[derive(Debug, Deserialize)]
+struct ListKeysResponse {
+    success: bool,
+    result: Option<Vec<ListedKey>>,
+    result_info: Option<ListKeysResultInfo>,
+    errors: Vec<CloudflareApiError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ListedKey {
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ListKeysResultInfo {
+    cursor: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CloudflareApiError {
+    code: i64,
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct KvWriteResponse {
+    success: bool,
+    errors: Vec<CloudflareApiError>,
+}
+
 pub struct CloudflareKvManager {
+    // Kept for future use of the crate's typed endpoints; KV per-key operations below
+    // go straight to the REST API since they need `expiration_ttl` and cursor paging.
+    #[allow(dead_code)]
     client: CloudflareClient,
+    http_client: reqwest::Client,
     account_id: String,
     namespace_id: String,
+    api_token: String,
 }
 
 impl CloudflareKvManager {
     pub async fn new(account_id: String, namespace_id: String, api_token: String) -> Result<Self, CloudflareError> {
         let credentials = Credentials::UserAuthToken {
-            token: api_token,
+            token: api_token.clone(),
         };
 
         let client = CloudflareClient::new(
@@ -40,73 +93,342 @@ impl CloudflareKvManager {
 
         Ok(Self {
             client,
+            http_client: reqwest::Client::new(),
             account_id,
             namespace_id,
+            api_token,
         })
     }
 
-    /// Serialize and store session data to Cloudflare KV
-    pub async fn store_sessions(&self, sessions: &HashMap<String, OidcIdentity>) -> Result<(), CloudflareError> {
-        let serialized = serde_json::to_string(sessions)
-            .map_err(CloudflareError::SerializationError)?;
+    fn namespace_url(&self) -> String {
+        format!(
+            "{}/accounts/{}/storage/kv/namespaces/{}",
+            CLOUDFLARE_API_BASE, self.account_id, self.namespace_id
+        )
+    }
 
-        // Store with timestamp key
-        let key = format!("sessions_{}", Utc::now().timestamp());
-        
-        // This is a simplified implementation
-        // In a real implementation, you'd use the Cloudflare KV API
-        // to store the serialized data
-        
-        tracing::info!("Storing {} sessions to Cloudflare KV with key: {}", sessions.len(), key);
-        
-        // For now, we'll just log the operation
-        // The actual KV operations would be implemented here
-        tracing::debug!("Serialized sessions: {}", serialized);
-        
+    fn check_errors(errors: &[CloudflareApiError]) -> Result<(), CloudflareError> {
+        if let Some(first) = errors.first() {
+            return Err(CloudflareError::ApiError(format!("{}: {}", first.code, first.message)));
+        }
+        Ok(())
+    }
+
+    /// Write a raw string value under `key` with the given TTL, raised to Cloudflare's
+    /// 60-second minimum when shorter. This is the primitive
+    /// every higher-level KV write (sessions, revocation, tower-sessions) builds on.
+    pub async fn put_value(&self, key: &str, value: &str, ttl_seconds: i64) -> Result<(), CloudflareError> {
+        let url = format!(
+            "{}/values/{}?expiration_ttl={}",
+            self.namespace_url(), key, ttl_seconds.max(60)
+        );
+
+        let response = self.http_client
+            .put(&url)
+            .bearer_auth(&self.api_token)
+            .header("Content-Type", "text/plain")
+            .body(value.to_string())
+            .send()
+            .await
+            .map_err(|e| CloudflareError::NetworkError(e.to_string()))?;
+
+        let parsed: KvWriteResponse = response.json().await
+            .map_err(|e| CloudflareError::NetworkError(e.to_string()))?;
+        Self::check_errors(&parsed.errors)?;
+        if !parsed.success {
+            return Err(CloudflareError::ApiError("KV write reported failure".to_string()));
+        }
         Ok(())
     }
 
-    /// Load session data from Cloudflare KV
+    /// Write a single session under `session:{session_id}`, letting Cloudflare expire
+    /// it automatically at `expires_at` instead of relying solely on our own sweep.
+    async fn put_session(&self, session_id: &str, identity: &OidcIdentity) -> Result<(), CloudflareError> {
+        let ttl_seconds = (identity.expires_at - Utc::now()).num_seconds();
+        let body = serde_json::to_string(identity).map_err(CloudflareError::SerializationError)?;
+        self.put_value(&format!("{}{}", SESSION_KEY_PREFIX, session_id), &body, ttl_seconds).await
+    }
+
+    pub async fn delete_key(&self, key: &str) -> Result<(), CloudflareError> {
+        let url = format!("{}/values/{}", self.namespace_url(), key);
+        let response = self.http_client
+            .delete(&url)
+            .bearer_auth(&self.api_token)
+            .send()
+            .await
+            .map_err(|e| CloudflareError::NetworkError(e.to_string()))?;
+
+        let parsed: KvWriteResponse = response.json().await
+            .map_err(|e| CloudflareError::NetworkError(e.to_string()))?;
+        Self::check_errors(&parsed.errors)?;
+        Ok(())
+    }
+
+    /// List every key under the session prefix, following the `cursor` until Cloudflare
+    /// reports there are no more pages.
+    async fn list_session_keys(&self) -> Result<Vec<String>, CloudflareError> {
+        self.list_keys_with_prefix(SESSION_KEY_PREFIX).await
+    }
+
+    /// List every key under `prefix`, following the `cursor` until Cloudflare reports
+    /// there are no more pages. Shared by `list_session_keys` and refresh-token loading.
+    async fn list_keys_with_prefix(&self, prefix: &str) -> Result<Vec<String>, CloudflareError> {
+        let mut keys = Vec::new();
+        let mut cursor: Option<String> = None;
+
+        loop {
+            let mut url = format!(
+                "{}/keys?prefix={}&limit=1000",
+                self.namespace_url(), prefix
+            );
+            if let Some(ref c) = cursor {
+                url.push_str(&format!("&cursor={}", c));
+            }
+
+            let response = self.http_client
+                .get(&url)
+                .bearer_auth(&self.api_token)
+                .send()
+                .await
+                .map_err(|e| CloudflareError::NetworkError(e.to_string()))?;
+
+            let parsed: ListKeysResponse = response.json().await
+                .map_err(|e| CloudflareError::NetworkError(e.to_string()))?;
+            Self::check_errors(&parsed.errors)?;
+            if !parsed.success {
+                return Err(CloudflareError::ApiError("KV list-keys reported failure".to_string()));
+            }
+
+            keys.extend(parsed.result.unwrap_or_default().into_iter().map(|k| k.name));
+
+            match parsed.result_info.and_then(|info| info.cursor).filter(|c| !c.is_empty()) {
+                Some(next_cursor) => cursor = Some(next_cursor),
+                None => break,
+            }
+        }
+
+        Ok(keys)
+    }
+
+    pub async fn get_value(&self, key: &str) -> Result<Option<String>, CloudflareError> {
+        let url = format!("{}/values/{}", self.namespace_url(), key);
+        let response = self.http_client
+            .get(&url)
+            .bearer_auth(&self.api_token)
+            .send()
+            .await
+            .map_err(|e| CloudflareError::NetworkError(e.to_string()))?;
+
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !response.status().is_success() {
+            return Err(CloudflareError::ApiError(format!("KV read returned {}", response.status())));
+        }
+
+        Ok(Some(response.text().await.map_err(|e| CloudflareError::NetworkError(e.to_string()))?))
+    }
+
+    /// Persist a session's current refresh-token record under `refresh:{session_id}`,
+    /// replacing whatever was there (e.g. after rotation). TTL is derived from the
+    /// record's own expiry rather than the session's.
+    pub async fn put_refresh_token(&self, session_id: &str, record: &RefreshTokenRecord) -> Result<(), CloudflareError> {
+        let ttl_seconds = (record.expires_at - Utc::now()).num_seconds();
+        let body = serde_json::to_string(record).map_err(CloudflareError::SerializationError)?;
+        self.put_value(&format!("{}{}", REFRESH_TOKEN_KEY_PREFIX, session_id), &body, ttl_seconds).await
+    }
+
+    /// Remove a session's refresh-token record, e.g. once reuse is detected and the
+    /// whole session is being revoked.
+    pub async fn delete_refresh_token(&self, session_id: &str) -> Result<(), CloudflareError> {
+        self.delete_key(&format!("{}{}", REFRESH_TOKEN_KEY_PREFIX, session_id)).await
+    }
+
+    /// Page through every `session:*` key, deserializing each value. Entries that fail
+    /// to parse (e.g. left over from an incompatible schema) are skipped and deleted
+    /// rather than surfaced as an error.
     pub async fn load_sessions(&self) -> Result<HashMap<String, OidcIdentity>, CloudflareError> {
-        // This is a simplified implementation
-        // In a real implementation, you'd:
-        // 1. List keys in the KV store
-        // 2. Find the most recent sessions key
-        // 3. Retrieve and deserialize the data
-        
-        tracing::info!("Loading sessions from Cloudflare KV");
-        
-        // For now, return empty sessions
-        // The actual KV operations would be implemented here
-        Ok(HashMap::new())
+        let keys = self.list_session_keys().await?;
+        let mut sessions = HashMap::new();
+
+        for key in keys {
+            let Some(value) = self.get_value(&key).await? else {
+                continue;
+            };
+
+            let session_id = key.trim_start_matches(SESSION_KEY_PREFIX).to_string();
+            match serde_json::from_str::<OidcIdentity>(&value) {
+                Ok(identity) => {
+                    sessions.insert(session_id, identity);
+                }
+                Err(e) => {
+                    tracing::warn!("Dropping unparseable session {}: {}", key, e);
+                    if let Err(delete_err) = self.delete_key(&key).await {
+                        tracing::warn!("Failed to delete unparseable session {}: {}", key, delete_err);
+                    }
+                }
+            }
+        }
+
+        tracing::info!("Loaded {} sessions from Cloudflare KV", sessions.len());
+        Ok(sessions)
     }
 
-    /// Clean up old session data from KV store
+    /// Page through every `refresh:*` key, deserializing each value. Mirrors
+    /// `load_sessions` so a restart restores outstanding refresh tokens instead of
+    /// forcing every session to re-login once its current access token expires.
+    pub async fn load_refresh_tokens(&self) -> Result<HashMap<String, RefreshTokenRecord>, CloudflareError> {
+        let keys = self.list_keys_with_prefix(REFRESH_TOKEN_KEY_PREFIX).await?;
+        let mut refresh_tokens = HashMap::new();
+
+        for key in keys {
+            let Some(value) = self.get_value(&key).await? else {
+                continue;
+            };
+
+            let session_id = key.trim_start_matches(REFRESH_TOKEN_KEY_PREFIX).to_string();
+            match serde_json::from_str::<RefreshTokenRecord>(&value) {
+                Ok(record) => {
+                    refresh_tokens.insert(session_id, record);
+                }
+                Err(e) => {
+                    tracing::warn!("Dropping unparseable refresh token {}: {}", key, e);
+                    if let Err(delete_err) = self.delete_key(&key).await {
+                        tracing::warn!("Failed to delete unparseable refresh token {}: {}", key, delete_err);
+                    }
+                }
+            }
+        }
+
+        tracing::info!("Loaded {} refresh tokens from Cloudflare KV", refresh_tokens.len());
+        Ok(refresh_tokens)
+    }
+
+    /// Safety-net sweep for sessions whose TTL somehow didn't take (e.g. written before
+    /// `expiration_ttl` support existed). KV's own per-key TTL is the primary mechanism.
     pub async fn cleanup_old_sessions(&self, keep_days: u32) -> Result<(), CloudflareError> {
-        let cutoff_timestamp = Utc::now().timestamp() - (keep_days as i64 * 24 * 60 * 60);
-        
-        tracing::info!("Cleaning up sessions older than {} days", keep_days);
-        
-        // This is a simplified implementation
-        // In a real implementation, you'd:
-        // 1. List all keys in the KV store
-        // 2. Filter keys older than cutoff_timestamp
-        // 3. Delete the old keys
-        
+        let cutoff = Utc::now() - chrono::Duration::days(keep_days as i64);
+        let keys = self.list_session_keys().await?;
+        let mut removed = 0;
+
+        for key in keys {
+            let Some(value) = self.get_value(&key).await? else {
+                continue;
+            };
+            if let Ok(identity) = serde_json::from_str::<OidcIdentity>(&value) {
+                if identity.expires_at < cutoff {
+                    self.delete_key(&key).await?;
+                    removed += 1;
+                }
+            }
+        }
+
+        tracing::info!("Cleaned up {} stale sessions older than {} days", removed, keep_days);
         Ok(())
     }
 }
 
+#[async_trait]
+impl crate::session_backend::SessionBackend for CloudflareKvManager {
+    async fn load_all(&self) -> Result<HashMap<String, OidcIdentity>, crate::session_backend::SessionBackendError> {
+        Ok(self.load_sessions().await?)
+    }
+
+    async fn put(&self, session_id: &str, identity: &OidcIdentity) -> Result<(), crate::session_backend::SessionBackendError> {
+        Ok(self.put_session(session_id, identity).await?)
+    }
+
+    async fn delete(&self, session_id: &str) -> Result<(), crate::session_backend::SessionBackendError> {
+        Ok(self.delete_key(&format!("{}{}", SESSION_KEY_PREFIX, session_id)).await?)
+    }
+
+    async fn cleanup_expired(&self, keep_days: u32) -> Result<(), crate::session_backend::SessionBackendError> {
+        Ok(self.cleanup_old_sessions(keep_days).await?)
+    }
+}
+
+/// Lets `Arc<CloudflareKvManager>` back an axum `SessionManagerLayer` directly (via
+/// `main`'s `SessionManagerLayer::new` over `AppState::kv_manager`), so routes that need
+/// cookie-based sessions don't have to hand-roll their own cookie handling.
+/// Implemented on the `Arc` rather than `CloudflareKvManager` itself since
+/// `SessionManagerLayer` requires its store to be `Clone`.
+#[async_trait]
+impl tower_sessions::session_store::SessionStore for Arc<CloudflareKvManager> {
+    async fn create(&self, record: &mut TowerSessionRecord) -> TowerSessionStoreResult<()> {
+        self.save(record).await
+    }
+
+    async fn save(&self, record: &TowerSessionRecord) -> TowerSessionStoreResult<()> {
+        let key = format!("{}{}", TOWER_SESSION_KEY_PREFIX, record.id);
+        let value = serde_json::to_string(record)
+            .map_err(|e| TowerSessionStoreError::Encode(e.to_string()))?;
+        let ttl_seconds = (record.expiry_date - time::OffsetDateTime::now_utc()).whole_seconds();
+
+        self.put_value(&key, &value, ttl_seconds)
+            .await
+            .map_err(|e| TowerSessionStoreError::Backend(e.to_string()))
+    }
+
+    async fn load(&self, session_id: &TowerSessionId) -> TowerSessionStoreResult<Option<TowerSessionRecord>> {
+        let key = format!("{}{}", TOWER_SESSION_KEY_PREFIX, session_id);
+        let value = self.get_value(&key).await
+            .map_err(|e| TowerSessionStoreError::Backend(e.to_string()))?;
+
+        match value {
+            Some(raw) => {
+                let record = serde_json::from_str(&raw)
+                    .map_err(|e| TowerSessionStoreError::Decode(e.to_string()))?;
+                Ok(Some(record))
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn delete(&self, session_id: &TowerSessionId) -> TowerSessionStoreResult<()> {
+        let key = format!("{}{}", TOWER_SESSION_KEY_PREFIX, session_id);
+        self.delete_key(&key).await
+            .map_err(|e| TowerSessionStoreError::Backend(e.to_string()))
+    }
+}
+
+impl std::fmt::Debug for CloudflareKvManager {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CloudflareKvManager")
+            .field("account_id", &self.account_id)
+            .field("namespace_id", &self.namespace_id)
+            .finish()
+    }
+}
+
 impl SessionStore {
-    /// Serialize sessions to Cloudflare KV
-    pub async fn serialize_to_kv(&self, kv_manager: &CloudflareKvManager) -> Result<(), CloudflareError> {
-        kv_manager.store_sessions(&self.sessions).await
+    /// Persist every in-memory session through `backend`, one `put` per session.
+    pub async fn serialize_to_kv(
+        &self,
+        backend: &dyn crate::session_backend::SessionBackend,
+    ) -> Result<(), crate::session_backend::SessionBackendError> {
+        for (session_id, identity) in &self.sessions {
+            backend.put(session_id, identity).await?;
+        }
+        Ok(())
     }
 
-    /// Load sessions from Cloudflare KV
-    pub async fn load_from_kv(&mut self, kv_manager: &CloudflareKvManager) -> Result<(), CloudflareError> {
-        let sessions = kv_manager.load_sessions().await?;
+    /// Replace in-memory sessions with whatever `backend` has persisted. When
+    /// `kv_manager` is set, also restores outstanding refresh tokens — those are
+    /// persisted directly through `CloudflareKvManager` rather than through the
+    /// generic `SessionBackend` trait, since no other backend supports them.
+    pub async fn load_from_kv(
+        &mut self,
+        backend: &dyn crate::session_backend::SessionBackend,
+        kv_manager: Option<&CloudflareKvManager>,
+    ) -> Result<(), crate::session_backend::SessionBackendError> {
+        let sessions = backend.load_all().await?;
         self.load_sessions(sessions);
+
+        if let Some(kv_manager) = kv_manager {
+            let refresh_tokens = kv_manager.load_refresh_tokens().await?;
+            self.load_refresh_tokens(refresh_tokens);
+        }
+
         Ok(())
     }
 }
@@ -126,6 +448,7 @@ mod tests {
             tier: UserTier::Normal,
             created_at: Utc::now(),
             expires_at: Utc::now() + chrono::Duration::days(7),
+            permission_overrides: Vec::new(),
         }
     }
 
@@ -0,0 +1,151 @@
+use crate::models::AppConfig;
+
+/// Names the config file to load. Its absence isn't an error: `load` falls back to
+/// `AppConfig::default()` before the environment overlay below is applied, so a
+/// from-env-only deployment still works.
+const CONFIG_PATH_VAR: &str = "AUTHFACE_CONFIG";
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    #[error("failed to read config file {0}: {1}")]
+    ReadFailed(String, std::io::Error),
+    #[error("failed to parse config file {0} as {1}: {2}")]
+    ParseFailed(String, &'static str, String),
+    #[error("invalid configuration: {0}")]
+    Invalid(String),
+}
+
+/// Build the effective `AppConfig`: a file named by `AUTHFACE_CONFIG` (if set, parsed
+/// as YAML or TOML by its extension), overlaid with environment variables, then
+/// validated. Environment variables always win over the file, matching the
+/// twelve-factor pattern the `CLOUDFLARE_*` vars already followed.
+pub fn load() -> Result<AppConfig, ConfigError> {
+    let mut config = match std::env::var(CONFIG_PATH_VAR) {
+        Ok(path) => load_file(&path)?,
+        Err(_) => AppConfig::default(),
+    };
+
+    apply_env_overlay(&mut config);
+    validate(&config)?;
+    Ok(config)
+}
+
+fn load_file(path: &str) -> Result<AppConfig, ConfigError> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| ConfigError::ReadFailed(path.to_string(), e))?;
+
+    if path.ends_with(".yaml") || path.ends_with(".yml") {
+        serde_yaml::from_str(&contents)
+            .map_err(|e| ConfigError::ParseFailed(path.to_string(), "YAML", e.to_string()))
+    } else {
+        toml::from_str(&contents)
+            .map_err(|e| ConfigError::ParseFailed(path.to_string(), "TOML", e.to_string()))
+    }
+}
+
+/// Layer `CLOUDFLARE_*`, `AUTHFACE_JWT_*`, and `AUTHFACE_BASE_URL` over whatever the
+/// config file (or the built-in default) provided. Each variable is applied only when
+/// set, so a deployment can mix file-provided and environment-provided values freely.
+fn apply_env_overlay(config: &mut AppConfig) {
+    if let Ok(v) = std::env::var("CLOUDFLARE_ACCOUNT_ID") {
+        config.cloudflare.account_id = v;
+    }
+    if let Ok(v) = std::env::var("CLOUDFLARE_NAMESPACE_ID") {
+        config.cloudflare.namespace_id = v;
+    }
+    if let Ok(v) = std::env::var("CLOUDFLARE_API_TOKEN") {
+        config.cloudflare.api_token = v;
+    }
+    if let Ok(v) = std::env::var("AUTHFACE_JWT_PRIVATE_KEY_PATH") {
+        config.security.jwt_private_key_path = v;
+    }
+    if let Ok(v) = std::env::var("AUTHFACE_JWT_PUBLIC_KEY_PATH") {
+        config.security.jwt_public_key_path = v;
+    }
+    if let Ok(v) = std::env::var("AUTHFACE_JWT_TTL_HOURS") {
+        if let Ok(hours) = v.parse() {
+            config.auth.jwt_ttl_hours = hours;
+        } else {
+            tracing::warn!("Ignoring AUTHFACE_JWT_TTL_HOURS={:?}: not a valid u32", v);
+        }
+    }
+    if let Ok(v) = std::env::var("AUTHFACE_BASE_URL") {
+        config.server.base_url = v;
+    }
+}
+
+/// Fail fast on a configuration that would otherwise surface as a confusing runtime
+/// error later (an unset key path, an OIDC provider with a blank secret, ...). Only
+/// checks that the key paths are non-empty; whether the files exist is left to
+/// `JwtManager::new`.
+fn validate(config: &AppConfig) -> Result<(), ConfigError> {
+    if config.server.base_url.is_empty() {
+        return Err(ConfigError::Invalid(
+            "server.base_url (or AUTHFACE_BASE_URL) must be set".to_string(),
+        ));
+    }
+    if config.security.jwt_private_key_path.is_empty() {
+        return Err(ConfigError::Invalid("security.jwt_private_key_path must be set".to_string()));
+    }
+    if config.security.jwt_public_key_path.is_empty() {
+        return Err(ConfigError::Invalid("security.jwt_public_key_path must be set".to_string()));
+    }
+    for (name, provider) in &config.oidc_providers {
+        if provider.client_id.is_empty() || provider.client_secret.is_empty() || provider.issuer.is_empty() {
+            return Err(ConfigError::Invalid(format!(
+                "oidc_providers.{} is missing client_id, client_secret, or issuer",
+                name
+            )));
+        }
+    }
+    let mut seen_usernames = std::collections::HashSet::new();
+    for seed in &config.local_accounts {
+        if seed.username.is_empty() || seed.password.is_empty() {
+            return Err(ConfigError::Invalid(
+                "local_accounts entries must set both username and password".to_string(),
+            ));
+        }
+        if !seen_usernames.insert(&seed.username) {
+            return Err(ConfigError::Invalid(format!(
+                "local_accounts.{} is listed more than once",
+                seed.username
+            )));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_rejects_empty_base_url() {
+        let mut config = AppConfig::default();
+        config.server.base_url = String::new();
+
+        assert!(matches!(validate(&config), Err(ConfigError::Invalid(_))));
+    }
+
+    #[test]
+    fn test_validate_rejects_incomplete_oidc_provider() {
+        let mut config = AppConfig::default();
+        config.oidc_providers.insert(
+            "google".to_string(),
+            crate::models::OidcProvider {
+                client_id: "client".to_string(),
+                client_secret: String::new(),
+                issuer: "https://accounts.google.com".to_string(),
+                discovery_url: "https://accounts.google.com/.well-known/openid-configuration".to_string(),
+                name: "Google".to_string(),
+            },
+        );
+
+        assert!(matches!(validate(&config), Err(ConfigError::Invalid(_))));
+    }
+
+    #[test]
+    fn test_default_config_is_valid() {
+        assert!(validate(&AppConfig::default()).is_ok());
+    }
+}
@@ -0,0 +1,179 @@
+use crate::models::{OidcIdentity, UserTier};
+use argon2::password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use chrono::{Duration, Utc};
+use std::collections::HashMap;
+
+/// The two ways a client can authenticate: an OIDC authorization-code callback, or a
+/// local username/password pair. Both end up producing the same `OidcIdentity`, so
+/// downstream JWT issuance and session handling don't need to know which was used.
+#[derive(Debug, Clone)]
+pub enum Credentials {
+    OidcCode {
+        provider: String,
+        code: String,
+        state: String,
+    },
+    UsernamePassword {
+        username: String,
+        password: String,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct LocalAccount {
+    pub sub: String,
+    pub email: Option<String>,
+    pub tier: UserTier,
+    password_hash: String,
+    /// Set by an operator to disable the account without deleting it. Checked after
+    /// password verification, so a blocked account still yields a distinct
+    /// `AccountBlocked` error rather than `InvalidCredentials`.
+    pub blocked: bool,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum LocalAccountError {
+    #[error("Username already registered: {0}")]
+    UsernameTaken(String),
+    #[error("Invalid username or password")]
+    InvalidCredentials,
+    #[error("Password hashing failed: {0}")]
+    HashError(String),
+    #[error("Account is blocked")]
+    AccountBlocked,
+}
+
+/// In-memory store of local (non-OIDC) accounts, keyed by username.
+pub struct LocalAccountManager {
+    local_accounts: HashMap<String, LocalAccount>,
+}
+
+impl LocalAccountManager {
+    pub fn new() -> Self {
+        Self {
+            local_accounts: HashMap::new(),
+        }
+    }
+
+    /// Hash `password` with Argon2id's default parameters and store a new account.
+    /// Rejects a username that's already taken.
+    pub fn register(&mut self, username: &str, password: &str, tier: UserTier) -> Result<(), LocalAccountError> {
+        if self.local_accounts.contains_key(username) {
+            return Err(LocalAccountError::UsernameTaken(username.to_string()));
+        }
+
+        let password_hash = Self::hash_password(password)?;
+
+        self.local_accounts.insert(username.to_string(), LocalAccount {
+            sub: format!("local:{}", username),
+            email: None,
+            tier,
+            password_hash,
+            blocked: false,
+        });
+
+        Ok(())
+    }
+
+    /// Hash `password` with Argon2id's default parameters, returning the PHC string
+    /// an operator can paste into a provisioning script without going through
+    /// `register` (e.g. to provision an account on a store backed by something other
+    /// than this in-memory map). `local_accounts` entries in the config file take a
+    /// plaintext password, which `register` hashes, not a PHC string.
+    pub fn hash_password(password: &str) -> Result<String, LocalAccountError> {
+        let salt = SaltString::generate(&mut OsRng);
+        Argon2::default()
+            .hash_password(password.as_bytes(), &salt)
+            .map_err(|e| LocalAccountError::HashError(e.to_string()))
+            .map(|hash| hash.to_string())
+    }
+
+    /// Set or clear `username`'s `blocked` flag, so an operator can disable an account
+    /// without deleting it.
+    pub fn set_blocked(&mut self, username: &str, blocked: bool) -> Result<(), LocalAccountError> {
+        let account = self.local_accounts.get_mut(username)
+            .ok_or(LocalAccountError::InvalidCredentials)?;
+        account.blocked = blocked;
+        Ok(())
+    }
+
+    /// Verify `password` against the stored Argon2id hash in constant time and, on
+    /// success, produce the same `OidcIdentity` shape an OIDC login would. A blocked
+    /// account still has its password checked first, so the distinction between
+    /// "wrong password" and "blocked" isn't observable to someone who doesn't know
+    /// the password.
+    pub fn authenticate(&self, username: &str, password: &str) -> Result<OidcIdentity, LocalAccountError> {
+        let account = self.local_accounts.get(username)
+            .ok_or(LocalAccountError::InvalidCredentials)?;
+
+        let parsed_hash = PasswordHash::new(&account.password_hash)
+            .map_err(|e| LocalAccountError::HashError(e.to_string()))?;
+        Argon2::default()
+            .verify_password(password.as_bytes(), &parsed_hash)
+            .map_err(|_| LocalAccountError::InvalidCredentials)?;
+
+        if account.blocked {
+            return Err(LocalAccountError::AccountBlocked);
+        }
+
+        Ok(OidcIdentity {
+            sub: account.sub.clone(),
+            name: None,
+            email: account.email.clone(),
+            provider: "local".to_string(),
+            tier: account.tier.clone(),
+            created_at: Utc::now(),
+            expires_at: Utc::now() + Duration::days(7),
+            permission_overrides: Vec::new(),
+        })
+    }
+}
+
+impl Default for LocalAccountManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_register_and_authenticate() {
+        let mut manager = LocalAccountManager::new();
+        manager.register("alice", "correct-horse-battery-staple", UserTier::Normal).unwrap();
+
+        let identity = manager.authenticate("alice", "correct-horse-battery-staple").unwrap();
+        assert_eq!(identity.sub, "local:alice");
+        assert_eq!(identity.provider, "local");
+    }
+
+    #[test]
+    fn test_authenticate_rejects_wrong_password() {
+        let mut manager = LocalAccountManager::new();
+        manager.register("alice", "correct-horse-battery-staple", UserTier::Normal).unwrap();
+
+        assert!(manager.authenticate("alice", "wrong-password").is_err());
+    }
+
+    #[test]
+    fn test_register_rejects_duplicate_username() {
+        let mut manager = LocalAccountManager::new();
+        manager.register("alice", "password1", UserTier::Normal).unwrap();
+
+        let result = manager.register("alice", "password2", UserTier::Normal);
+        assert!(matches!(result, Err(LocalAccountError::UsernameTaken(_))));
+    }
+
+    #[test]
+    fn test_authenticate_rejects_blocked_account() {
+        let mut manager = LocalAccountManager::new();
+        manager.register("alice", "correct-horse-battery-staple", UserTier::Normal).unwrap();
+        manager.set_blocked("alice", true).unwrap();
+
+        let result = manager.authenticate("alice", "correct-horse-battery-staple");
+        assert!(matches!(result, Err(LocalAccountError::AccountBlocked)));
+    }
+}
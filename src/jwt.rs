@@ -1,10 +1,61 @@
-use crate::models::{JwtClaims, OidcIdentity, UserTier};
+use crate::cloudflare::{CloudflareError, CloudflareKvManager};
+use crate::models::{JwtClaims, OidcIdentity, RefreshTokenRecord, UserTier};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
 use chrono::{Duration, Utc};
 use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use rand::rngs::OsRng;
+use rand::RngCore;
 use serde_json;
+use sha2::{Digest, Sha256};
 use std::fs;
 use uuid::Uuid;
 
+/// Key prefix under which revoked `jti`s are recorded in Cloudflare KV.
+const REVOKED_KEY_PREFIX: &str = "revoked:";
+
+/// Scopes a token to the single action it was minted for, following the vaultwarden
+/// model of issuer-scoped tokens: a token minted to verify an email address can't
+/// also be replayed as a login token. Each variant carries its own default TTL,
+/// distinct from the general-purpose login token's `AuthConfig::jwt_ttl_hours`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenPurpose {
+    /// General-purpose session token, minted on OIDC/local-account login.
+    Login,
+    /// Invitation to create an account, sent by an existing admin.
+    Invite,
+    /// Confirms control of an email address.
+    VerifyEmail,
+    /// One-shot confirmation of an account-deletion request.
+    Delete,
+    /// Elevated, short-lived token for a specific administrative action.
+    Admin,
+}
+
+impl TokenPurpose {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TokenPurpose::Login => "login",
+            TokenPurpose::Invite => "invite",
+            TokenPurpose::VerifyEmail => "verifyemail",
+            TokenPurpose::Delete => "delete",
+            TokenPurpose::Admin => "admin",
+        }
+    }
+
+    /// Default lifetime for a token minted for this purpose. `Login`'s default is
+    /// overridden in practice by `AuthConfig::jwt_ttl_hours`; the others are fixed,
+    /// short-lived windows appropriate to a one-shot confirmation link.
+    pub fn default_ttl_hours(&self) -> u32 {
+        match self {
+            TokenPurpose::Login => 24,
+            TokenPurpose::Invite => 120,
+            TokenPurpose::VerifyEmail => 24,
+            TokenPurpose::Delete => 1,
+            TokenPurpose::Admin => 1,
+        }
+    }
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum JwtError {
     #[error("JWT encoding error: {0}")]
@@ -15,6 +66,10 @@ pub enum JwtError {
     KeyFileNotFound(String),
     #[error("Invalid key format: {0}")]
     InvalidKeyFormat(String),
+    #[error("Token has been revoked")]
+    Revoked,
+    #[error("Revocation store error: {0}")]
+    RevocationStoreError(CloudflareError),
 }
 
 pub struct JwtManager {
@@ -46,11 +101,22 @@ impl JwtManager {
         })
     }
 
-    /// Create a JWT token from an OIDC identity
-    pub fn create_token(&self, identity: &OidcIdentity, ttl_hours: u32) -> Result<String, JwtError> {
+    /// Create a JWT token from an OIDC identity, scoped to `purpose` via the `iss`/`aud`
+    /// claims so it can't be replayed for a different action (see `TokenPurpose`).
+    /// `mfa_completed` should be `true` only when the identity enrolled a TOTP factor
+    /// and has already passed `verify_totp` for this session, so downstream
+    /// authorization can require it for sensitive permissions.
+    pub fn create_token(
+        &self,
+        identity: &OidcIdentity,
+        ttl_hours: u32,
+        mfa_completed: bool,
+        purpose: TokenPurpose,
+        issuer: &str,
+    ) -> Result<String, JwtError> {
         let now = Utc::now();
         let exp = now + Duration::hours(ttl_hours as i64);
-        
+
         let claims = JwtClaims {
             sub: identity.sub.clone(),
             name: identity.name.clone(),
@@ -60,6 +126,10 @@ impl JwtManager {
             iat: now.timestamp(),
             exp: exp.timestamp(),
             jti: Uuid::new_v4().to_string(),
+            mfa_completed,
+            iss: format!("{}|{}", issuer, purpose.as_str()),
+            aud: purpose.as_str().to_string(),
+            permission_overrides: identity.permission_overrides.clone(),
         };
 
         let header = Header::new(self.algorithm);
@@ -67,10 +137,22 @@ impl JwtManager {
             .map_err(JwtError::EncodingError)
     }
 
-    /// Verify and decode a JWT token
-    pub fn verify_token(&self, token: &str) -> Result<JwtClaims, JwtError> {
-        let validation = Validation::new(self.algorithm);
-        
+    /// Verify and decode a JWT token, requiring it was minted for `expected_purpose` by
+    /// `issuer` (a token minted for e.g. `TokenPurpose::VerifyEmail` is rejected here
+    /// even if otherwise valid). When a KV manager is supplied, also rejects tokens
+    /// whose `jti` has been revoked (logout, admin-forced revocation) even if the
+    /// signature and `exp` are otherwise still valid.
+    pub async fn verify_token(
+        &self,
+        token: &str,
+        kv_manager: Option<&CloudflareKvManager>,
+        expected_purpose: TokenPurpose,
+        issuer: &str,
+    ) -> Result<JwtClaims, JwtError> {
+        let mut validation = Validation::new(self.algorithm);
+        validation.set_issuer(&[format!("{}|{}", issuer, expected_purpose.as_str())]);
+        validation.set_audience(&[expected_purpose.as_str()]);
+
         let token_data = decode::<JwtClaims>(token, &self.decoding_key, &validation)
             .map_err(JwtError::DecodingError)?;
 
@@ -82,9 +164,93 @@ impl JwtManager {
             ));
         }
 
+        if let Some(kv_manager) = kv_manager {
+            if self.is_revoked(kv_manager, &token_data.claims.jti).await? {
+                return Err(JwtError::Revoked);
+            }
+        }
+
         Ok(token_data.claims)
     }
 
+    /// Record `jti` as revoked so a later `verify_token` rejects it before its natural
+    /// `exp`. The KV entry's own TTL is set to the token's remaining lifetime so the
+    /// blocklist never grows past what's still usable.
+    pub async fn revoke(&self, kv_manager: &CloudflareKvManager, claims: &JwtClaims) -> Result<(), JwtError> {
+        let remaining_seconds = claims.exp - Utc::now().timestamp();
+        if remaining_seconds <= 0 {
+            return Ok(()); // Already expired; nothing to revoke.
+        }
+
+        kv_manager
+            .put_value(&format!("{}{}", REVOKED_KEY_PREFIX, claims.jti), "1", remaining_seconds)
+            .await
+            .map_err(JwtError::RevocationStoreError)
+    }
+
+    async fn is_revoked(&self, kv_manager: &CloudflareKvManager, jti: &str) -> Result<bool, JwtError> {
+        kv_manager
+            .get_value(&format!("{}{}", REVOKED_KEY_PREFIX, jti))
+            .await
+            .map(|v| v.is_some())
+            .map_err(JwtError::RevocationStoreError)
+    }
+
+    /// Mint a `TokenPurpose::Login` access token alongside a fresh opaque refresh
+    /// token. Only a SHA-256 hash of the refresh token is returned for storage (in
+    /// `RefreshTokenRecord`); the raw value is handed to the caller exactly once and
+    /// never stored.
+    pub fn create_token_pair(
+        &self,
+        identity: &OidcIdentity,
+        ttl_hours: u32,
+        mfa_completed: bool,
+        refresh_token_size: usize,
+        refresh_token_expire_days: i64,
+        issuer: &str,
+    ) -> Result<(String, String, RefreshTokenRecord), JwtError> {
+        let access_token = self.create_token(identity, ttl_hours, mfa_completed, TokenPurpose::Login, issuer)?;
+
+        let mut raw_bytes = vec![0u8; refresh_token_size];
+        OsRng.fill_bytes(&mut raw_bytes);
+        let raw_refresh_token = URL_SAFE_NO_PAD.encode(&raw_bytes);
+
+        let record = RefreshTokenRecord {
+            sub: identity.sub.clone(),
+            token_hash: Self::hash_refresh_token(&raw_refresh_token),
+            expires_at: Utc::now() + Duration::days(refresh_token_expire_days),
+            used: false,
+        };
+
+        Ok((access_token, raw_refresh_token, record))
+    }
+
+    /// Check `raw_refresh_token` against `record`: unexpired, unused, and matching
+    /// the stored hash. Does NOT mark the record used — callers rotate it themselves
+    /// once they've decided to honor the request.
+    pub fn verify_refresh_token(raw_refresh_token: &str, record: &RefreshTokenRecord) -> Result<(), JwtError> {
+        if record.used {
+            return Err(JwtError::Revoked);
+        }
+        if record.expires_at < Utc::now() {
+            return Err(JwtError::DecodingError(
+                jsonwebtoken::errors::ErrorKind::ExpiredSignature.into()
+            ));
+        }
+        if Self::hash_refresh_token(raw_refresh_token) != record.token_hash {
+            return Err(JwtError::DecodingError(
+                jsonwebtoken::errors::ErrorKind::InvalidToken.into()
+            ));
+        }
+        Ok(())
+    }
+
+    fn hash_refresh_token(raw_refresh_token: &str) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(raw_refresh_token.as_bytes());
+        format!("{:x}", hasher.finalize())
+    }
+
     /// Extract user tier from JWT claims
     pub fn extract_tier(claims: &JwtClaims) -> UserTier {
         match claims.tier.as_str() {
@@ -112,6 +278,7 @@ mod tests {
             tier: UserTier::Normal,
             created_at: Utc::now(),
             expires_at: Utc::now() + Duration::days(7),
+            permission_overrides: Vec::new(),
         }
     }
 
@@ -121,11 +288,47 @@ mod tests {
         // In a real test environment, you'd generate test keys
         // or use a test key pair
         let identity = create_test_identity();
-        
+
         // Test would verify that:
         // 1. Token can be created from identity
         // 2. Token can be verified and decoded
         // 3. Claims match the original identity
         // 4. Expired tokens are rejected
     }
+
+    fn create_test_refresh_record(raw_token: &str) -> crate::models::RefreshTokenRecord {
+        crate::models::RefreshTokenRecord {
+            sub: "test_user_123".to_string(),
+            token_hash: JwtManager::hash_refresh_token(raw_token),
+            expires_at: Utc::now() + Duration::days(30),
+            used: false,
+        }
+    }
+
+    #[test]
+    fn test_verify_refresh_token_accepts_valid_record() {
+        let record = create_test_refresh_record("a-raw-refresh-token");
+        assert!(JwtManager::verify_refresh_token("a-raw-refresh-token", &record).is_ok());
+    }
+
+    #[test]
+    fn test_verify_refresh_token_rejects_hash_mismatch() {
+        let record = create_test_refresh_record("a-raw-refresh-token");
+        assert!(JwtManager::verify_refresh_token("a-different-token", &record).is_err());
+    }
+
+    #[test]
+    fn test_verify_refresh_token_rejects_expired_record() {
+        let mut record = create_test_refresh_record("a-raw-refresh-token");
+        record.expires_at = Utc::now() - Duration::hours(1);
+        assert!(JwtManager::verify_refresh_token("a-raw-refresh-token", &record).is_err());
+    }
+
+    #[test]
+    fn test_verify_refresh_token_rejects_used_record() {
+        let mut record = create_test_refresh_record("a-raw-refresh-token");
+        record.used = true;
+        let result = JwtManager::verify_refresh_token("a-raw-refresh-token", &record);
+        assert!(matches!(result, Err(JwtError::Revoked)));
+    }
 }
\ No newline at end of file
@@ -1,8 +1,15 @@
 mod models;
+mod config;
 mod jwt;
 mod oidc;
 mod cloudflare;
+mod credentials;
+mod extractors;
+mod permissions;
+mod redis_backend;
+mod session_backend;
 mod tests;
+mod totp;
 
 use axum::{
     extract::{Query, State},
@@ -11,6 +18,9 @@ use axum::{
     routing::{get, post},
     Router,
 };
+use axum_extra::headers::authorization::Basic;
+use axum_extra::headers::Authorization;
+use axum_extra::TypedHeader;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::Arc;
@@ -20,10 +30,16 @@ use tower_http::trace::TraceLayer;
 use tracing::{info, Level};
 use tracing_subscriber;
 
-use models::{AppConfig, SessionStore, OidcIdentity, UserTier};
-use jwt::JwtManager;
+use models::{AppConfig, SessionBackendConfig, SessionStore, OidcIdentity, UserTier};
+use credentials::{Credentials, LocalAccountError, LocalAccountManager};
+use jwt::{JwtError, JwtManager};
 use oidc::OidcManager;
 use cloudflare::CloudflareKvManager;
+use extractors::{AuthenticatedUser, RequirePermission, ViewStatusPermission};
+use permissions::PermissionPolicy;
+use redis_backend::RedisSessionBackend;
+use session_backend::SessionBackend;
+use totp::TotpManager;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HealthResponse {
@@ -38,12 +54,42 @@ pub struct StatusResponse {
     pub uptime: String,
 }
 
+/// An `OidcIdentity` parked pending `/mfa/verify`, plus when it was parked so the
+/// cleanup task can sweep entries abandoned mid-login (e.g. the user never returns
+/// with a code) instead of letting them accumulate in memory forever.
+#[derive(Debug, Clone)]
+pub struct PendingMfaEntry {
+    pub identity: OidcIdentity,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Age past which the hourly cleanup task sweeps a `pending_mfa` entry that has had
+/// no successful `/mfa/verify`; `/mfa/verify` itself doesn't check the age, so an
+/// entry stays redeemable until the next sweep after this. Generous relative to the ~30s TOTP step so a user who's
+/// slow to find their authenticator app isn't locked out, but still bounded.
+fn pending_mfa_ttl() -> chrono::Duration {
+    chrono::Duration::minutes(10)
+}
+
 #[derive(Debug, Clone)]
 pub struct AppState {
     pub session_store: Arc<RwLock<SessionStore>>,
     pub jwt_manager: JwtManager,
     pub oidc_manager: OidcManager,
-    pub kv_manager: Option<CloudflareKvManager>,
+    /// Raw Cloudflare KV access for JWT revocation (`jti` blocklist) and refresh-token
+    /// storage, which haven't been generalized past Cloudflare KV the way
+    /// `session_backend` has.
+    pub kv_manager: Option<Arc<CloudflareKvManager>>,
+    /// Backs `SessionStore`'s persistence; selected by `AppConfig::session_backend`.
+    pub session_backend: Option<Arc<dyn SessionBackend>>,
+    pub permission_policy: Arc<PermissionPolicy>,
+    pub totp_manager: Arc<RwLock<TotpManager>>,
+    /// Local (non-OIDC) username/password accounts, checked by `/login`.
+    pub local_accounts: Arc<RwLock<LocalAccountManager>>,
+    /// Identities that passed OIDC/local-account verification but whose owner has an
+    /// enrolled TOTP factor, keyed by a one-time `pending_id`. Promoted to a full
+    /// session and JWT only once `/mfa/verify` succeeds.
+    pub pending_mfa: Arc<RwLock<HashMap<String, PendingMfaEntry>>>,
     pub config: AppConfig,
     pub start_time: std::time::Instant,
 }
@@ -57,8 +103,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     info!("Starting AuthFace service");
 
-    // Load configuration
-    let config = load_config().await?;
+    // Load configuration: a file named by `AUTHFACE_CONFIG`, overlaid with
+    // environment variables, then validated.
+    let config = config::load()?;
 
     // Initialize JWT manager
     let jwt_manager = JwtManager::new(
@@ -69,72 +116,128 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Initialize OIDC manager
     let oidc_manager = OidcManager::new(config.oidc_providers.clone());
 
-    // Initialize Cloudflare KV manager (if configured)
+    // Initialize the Cloudflare KV manager. This is kept around regardless of which
+    // `SessionBackend` stores sessions, since JWT revocation and refresh-token storage
+    // haven't been generalized past Cloudflare KV yet.
     let kv_manager = if !config.cloudflare.account_id.is_empty() {
-        Some(CloudflareKvManager::new(
+        Some(Arc::new(CloudflareKvManager::new(
             config.cloudflare.account_id.clone(),
             config.cloudflare.namespace_id.clone(),
             config.cloudflare.api_token.clone(),
-        ).await?)
+        ).await?))
     } else {
         None
     };
 
+    // Build the session backend selected by `AppConfig::session_backend`. Reuses the
+    // Cloudflare KV manager above when that's the chosen backend, rather than opening
+    // a second connection.
+    let session_backend: Option<Arc<dyn SessionBackend>> = match &config.session_backend {
+        SessionBackendConfig::Cloudflare => {
+            kv_manager.clone().map(|m| m as Arc<dyn SessionBackend>)
+        }
+        SessionBackendConfig::Redis { url, pool_size } => {
+            Some(Arc::new(RedisSessionBackend::new(url, *pool_size as usize)?) as Arc<dyn SessionBackend>)
+        }
+    };
+
     // Create session store
     let mut session_store = SessionStore::new();
 
-    // Load existing sessions from KV store if available
-    if let Some(ref kv_manager) = kv_manager {
-        if let Err(e) = session_store.load_from_kv(kv_manager).await {
-            tracing::warn!("Failed to load sessions from KV store: {}", e);
+    // Load existing sessions from the configured backend, if any
+    if let Some(ref backend) = session_backend {
+        if let Err(e) = session_store.load_from_kv(backend.as_ref(), kv_manager.as_deref()).await {
+            tracing::warn!("Failed to load sessions from session backend: {}", e);
+        }
+    }
+
+    // Provision local accounts named in config. This is the only way to create one:
+    // there's no registration endpoint, so an account only exists if an operator put
+    // it in `AUTHFACE_CONFIG`.
+    let mut local_account_manager = LocalAccountManager::new();
+    for seed in &config.local_accounts {
+        if let Err(e) = local_account_manager.register(&seed.username, &seed.password, seed.tier.clone()) {
+            tracing::warn!("Failed to provision local account {:?}: {}", seed.username, e);
         }
     }
 
     // Create application state
+    let permission_policy = Arc::new(PermissionPolicy::from_config(&config.auth));
     let app_state = AppState {
         session_store: Arc::new(RwLock::new(session_store)),
         jwt_manager,
         oidc_manager,
         kv_manager,
+        session_backend,
+        permission_policy,
+        totp_manager: Arc::new(RwLock::new(TotpManager::new())),
+        local_accounts: Arc::new(RwLock::new(local_account_manager)),
+        pending_mfa: Arc::new(RwLock::new(HashMap::new())),
         config,
         start_time: std::time::Instant::now(),
     };
 
     // Start cleanup task
     let session_store_clone = app_state.session_store.clone();
-    let kv_manager_clone = app_state.kv_manager.clone();
+    let session_backend_clone = app_state.session_backend.clone();
+    let pending_mfa_clone = app_state.pending_mfa.clone();
     tokio::spawn(async move {
         let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(3600)); // Every hour
         loop {
             interval.tick().await;
-            
+
             let mut store = session_store_clone.write().await;
             let removed_count = store.cleanup_expired();
-            
+
             if removed_count > 0 {
                 tracing::info!("Cleaned up {} expired sessions", removed_count);
-                
-                // Serialize to KV store if available
-                if let Some(ref kv_manager) = kv_manager_clone {
-                    if let Err(e) = store.serialize_to_kv(kv_manager).await {
-                        tracing::error!("Failed to serialize sessions to KV store: {}", e);
+
+                // Serialize to the session backend, if configured
+                if let Some(ref backend) = session_backend_clone {
+                    if let Err(e) = store.serialize_to_kv(backend.as_ref()).await {
+                        tracing::error!("Failed to serialize sessions to session backend: {}", e);
                     }
                 }
             }
+            drop(store);
+
+            // Sweep pending MFA entries that were never completed, so an abandoned
+            // login doesn't sit in memory forever.
+            let cutoff = chrono::Utc::now() - pending_mfa_ttl();
+            let mut pending = pending_mfa_clone.write().await;
+            let pending_before = pending.len();
+            pending.retain(|_, entry| entry.created_at > cutoff);
+            let pending_removed = pending_before - pending.len();
+            if pending_removed > 0 {
+                tracing::info!("Cleaned up {} abandoned pending-MFA logins", pending_removed);
+            }
         }
     });
 
+    // Back an axum `SessionManagerLayer` with Cloudflare KV when it's configured, so
+    // cookie-based sessions are available to routes without hand-rolling cookie
+    // handling. Absent when no Cloudflare account is configured, same as `kv_manager`
+    // itself.
+    let session_manager_layer = app_state.kv_manager.clone().map(tower_sessions::SessionManagerLayer::new);
+
     // Build our application with routes
     let app = Router::new()
         .route("/health", get(health_handler))
         .route("/status", get(status_handler))
         .route("/auth/:provider", get(auth_handler))
         .route("/callback/:provider", get(callback_handler))
+        .route("/login", post(login_handler))
         .route("/token", post(token_handler))
+        .route("/refresh", post(refresh_handler))
         .route("/verify", post(verify_handler))
+        .route("/logout", post(logout_handler))
+        .route("/admin/sessions", get(admin_sessions_handler))
+        .route("/mfa/enroll", post(mfa_enroll_handler))
+        .route("/mfa/verify", post(mfa_verify_handler))
         .route("/", get(root_handler))
         .layer(CorsLayer::permissive())
         .layer(TraceLayer::new_for_http())
+        .layer(session_manager_layer)
         .with_state(app_state);
 
     // Run the server
@@ -167,11 +270,12 @@ async fn auth_handler(
     axum::extract::Path(provider): axum::extract::Path<String>,
     State(state): State<AppState>,
 ) -> Result<Json<serde_json::Value>, StatusCode> {
-    let redirect_uri = format!("http://localhost:8080/callback/{}", provider);
-    
-    match state.oidc_manager.get_authorization_url(&provider, &redirect_uri) {
-        Ok(auth_url) => Ok(Json(serde_json::json!({
+    let redirect_uri = format!("{}/callback/{}", state.config.server.base_url, provider);
+
+    match state.oidc_manager.get_authorization_url(&provider, &redirect_uri).await {
+        Ok((auth_url, state)) => Ok(Json(serde_json::json!({
             "auth_url": auth_url,
+            "state": state,
             "provider": provider
         }))),
         Err(_) => Err(StatusCode::BAD_REQUEST),
@@ -184,104 +288,322 @@ async fn callback_handler(
     State(state): State<AppState>,
 ) -> Result<Json<serde_json::Value>, StatusCode> {
     let code = params.get("code").ok_or(StatusCode::BAD_REQUEST)?;
-    let redirect_uri = format!("http://localhost:8080/callback/{}", provider);
-    
-    match state.oidc_manager.exchange_code(&provider, code, &redirect_uri).await {
-        Ok(identity) => {
-            let session_id = uuid::Uuid::new_v4().to_string();
-            
-            // Store session
-            {
-                let mut store = state.session_store.write().await;
-                store.add_session(session_id.clone(), identity.clone());
-            }
-            
-            // Create JWT token
-            match state.jwt_manager.create_token(&identity, state.config.auth.jwt_ttl_hours) {
-                Ok(token) => Ok(Json(serde_json::json!({
-                    "token": token,
-                    "session_id": session_id,
-                    "user": {
-                        "sub": identity.sub,
-                        "name": identity.name,
-                        "email": identity.email,
-                        "tier": identity.tier.as_str(),
-                        "provider": identity.provider
-                    }
-                }))),
-                Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
-            }
+    let callback_state = params.get("state").ok_or(StatusCode::BAD_REQUEST)?;
+
+    let identity = authenticate_credentials(&state, Credentials::OidcCode {
+        provider,
+        code: code.clone(),
+        state: callback_state.clone(),
+    }).await?;
+
+    start_login(&state, identity).await
+}
+
+/// Authenticate a local (non-OIDC) username/password account, accepting either an
+/// `Authorization: Basic` header or a `{"username", "password"}` JSON body. On
+/// success this mirrors `callback_handler`: a TOTP-enrolled account is parked
+/// pending `/mfa/verify` instead of completing the login directly. A blocked
+/// account yields `403` rather than the `401` used for wrong credentials.
+async fn login_handler(
+    State(state): State<AppState>,
+    basic_auth: Option<TypedHeader<Authorization<Basic>>>,
+    body: axum::body::Bytes,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let (username, password) = if let Some(TypedHeader(Authorization(basic))) = basic_auth {
+        (basic.username().to_string(), basic.password().to_string())
+    } else {
+        let payload: serde_json::Value = serde_json::from_slice(&body)
+            .map_err(|_| StatusCode::BAD_REQUEST)?;
+        let username = payload.get("username")
+            .and_then(|v| v.as_str())
+            .ok_or(StatusCode::BAD_REQUEST)?
+            .to_string();
+        let password = payload.get("password")
+            .and_then(|v| v.as_str())
+            .ok_or(StatusCode::BAD_REQUEST)?
+            .to_string();
+        (username, password)
+    };
+
+    let identity = authenticate_credentials(&state, Credentials::UsernamePassword { username, password }).await?;
+    start_login(&state, identity).await
+}
+
+/// Verify `credentials` against the provider it names (OIDC authorization-code
+/// exchange, or a local Argon2 account) and produce the resulting `OidcIdentity`.
+/// `callback_handler` and `login_handler` differ only in how they gather
+/// `Credentials`; this is the one place that turns either kind into an identity, so
+/// downstream session/JWT handling never needs to know which was used.
+async fn authenticate_credentials(state: &AppState, credentials: Credentials) -> Result<OidcIdentity, StatusCode> {
+    match credentials {
+        Credentials::OidcCode { provider, code, state: callback_state } => {
+            let redirect_uri = format!("{}/callback/{}", state.config.server.base_url, provider);
+            state.oidc_manager.exchange_code(&provider, &code, &redirect_uri, &callback_state).await
+                .map_err(|_| StatusCode::BAD_REQUEST)
+        }
+        Credentials::UsernamePassword { username, password } => {
+            state.local_accounts.read().await.authenticate(&username, &password).map_err(|e| match e {
+                LocalAccountError::AccountBlocked => StatusCode::FORBIDDEN,
+                _ => StatusCode::UNAUTHORIZED,
+            })
         }
-        Err(_) => Err(StatusCode::BAD_REQUEST),
     }
 }
 
+/// Park `identity` pending `/mfa/verify` if it has an enrolled TOTP factor, otherwise
+/// complete the login immediately. Shared by `callback_handler` and `login_handler`
+/// now that both produce an `OidcIdentity` through `authenticate_credentials`.
+async fn start_login(state: &AppState, identity: OidcIdentity) -> Result<Json<serde_json::Value>, StatusCode> {
+    if state.totp_manager.read().await.is_enrolled(&identity.sub) {
+        let pending_id = uuid::Uuid::new_v4().to_string();
+        let entry = PendingMfaEntry { identity, created_at: chrono::Utc::now() };
+        state.pending_mfa.write().await.insert(pending_id.clone(), entry);
+        return Ok(Json(serde_json::json!({
+            "mfa_required": true,
+            "pending_id": pending_id
+        })));
+    }
+
+    complete_login(state, identity, false).await
+}
+
+/// Finish a login by starting a session and minting a JWT. Shared by the direct
+/// (no second factor) path and `/mfa/verify` once TOTP has been confirmed.
+async fn complete_login(
+    state: &AppState,
+    identity: OidcIdentity,
+    mfa_completed: bool,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let session_id = uuid::Uuid::new_v4().to_string();
+
+    let (token, refresh_token) = match state.jwt_manager.create_token_pair(
+        &identity,
+        state.config.auth.jwt_ttl_hours,
+        mfa_completed,
+        state.config.auth.refresh_token_size,
+        state.config.auth.refresh_token_expire_days,
+        &state.config.auth.token_issuer,
+    ) {
+        Ok((token, refresh_token, record)) => {
+            let mut store = state.session_store.write().await;
+            store.add_session(session_id.clone(), identity.clone());
+            store.set_refresh_token(session_id.clone(), record.clone());
+            drop(store);
+
+            if let Some(ref kv_manager) = state.kv_manager {
+                if let Err(e) = kv_manager.put_refresh_token(&session_id, &record).await {
+                    tracing::warn!("Failed to persist refresh token to KV store: {}", e);
+                }
+            }
+
+            (token, refresh_token)
+        }
+        Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR),
+    };
+
+    Ok(Json(serde_json::json!({
+        "token": token,
+        "refresh_token": refresh_token,
+        "session_id": session_id,
+        "user": {
+            "sub": identity.sub,
+            "name": identity.name,
+            "email": identity.email,
+            "tier": identity.tier.as_str(),
+            "provider": identity.provider
+        }
+    })))
+}
+
+/// Enroll the caller in TOTP and return the secret plus an `otpauth://` URI for
+/// display as a QR code. Gated behind `AuthenticatedUser` so a caller can only ever
+/// enroll their own `sub` (taken from their verified token, not request input) —
+/// otherwise anyone who knew or guessed a victim's `sub` could overwrite their TOTP
+/// secret via `TotpManager::enroll_totp`'s unconditional overwrite.
+async fn mfa_enroll_handler(
+    State(state): State<AppState>,
+    AuthenticatedUser(claims): AuthenticatedUser,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let (secret, otpauth_uri) = state.totp_manager.write().await.enroll_totp(&claims.sub, "AuthFace");
+
+    Ok(Json(serde_json::json!({
+        "secret": secret,
+        "otpauth_uri": otpauth_uri
+    })))
+}
+
+/// Complete a login parked by `start_login` once the caller supplies a valid TOTP
+/// code. Looks up the pending entry without removing it, so a mistyped code can be
+/// retried within the TOTP step window instead of forcing the user to restart the
+/// whole login from scratch; the entry is only removed once `verify_totp` succeeds.
+async fn mfa_verify_handler(
+    State(state): State<AppState>,
+    Json(payload): Json<serde_json::Value>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let pending_id = payload.get("pending_id")
+        .and_then(|v| v.as_str())
+        .ok_or(StatusCode::BAD_REQUEST)?;
+    let code = payload.get("code")
+        .and_then(|v| v.as_str())
+        .ok_or(StatusCode::BAD_REQUEST)?;
+
+    let identity = {
+        let pending = state.pending_mfa.read().await;
+        let entry = pending.get(pending_id).ok_or(StatusCode::NOT_FOUND)?;
+        entry.identity.clone()
+    };
+
+    state.totp_manager.write().await.verify_totp(&identity.sub, code)
+        .map_err(|_| StatusCode::UNAUTHORIZED)?;
+
+    state.pending_mfa.write().await.remove(pending_id);
+
+    complete_login(&state, identity, true).await
+}
+
+/// Mint a fresh access token for `session_id`, in place of waiting out the current
+/// token's TTL. Requires the caller to already present a valid bearer token for the
+/// same `sub` via `AuthenticatedUser` — without that, anyone who learned a
+/// `session_id` (e.g. from a log line) could mint themselves a live token for it.
 async fn token_handler(
     State(state): State<AppState>,
+    AuthenticatedUser(claims): AuthenticatedUser,
     Json(payload): Json<serde_json::Value>,
 ) -> Result<Json<serde_json::Value>, StatusCode> {
     let session_id = payload.get("session_id")
         .and_then(|v| v.as_str())
         .ok_or(StatusCode::BAD_REQUEST)?;
-    
+
     let store = state.session_store.read().await;
-    if let Some(identity) = store.get_session(session_id) {
-        match state.jwt_manager.create_token(identity, state.config.auth.jwt_ttl_hours) {
-            Ok(token) => Ok(Json(serde_json::json!({
-                "token": token,
-                "expires_in": state.config.auth.jwt_ttl_hours * 3600
-            }))),
-            Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
-        }
-    } else {
-        Err(StatusCode::UNAUTHORIZED)
+    let identity = store.get_session(session_id).ok_or(StatusCode::UNAUTHORIZED)?;
+
+    if identity.sub != claims.sub {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    match state.jwt_manager.create_token(identity, state.config.auth.jwt_ttl_hours, false, jwt::TokenPurpose::Login, &state.config.auth.token_issuer) {
+        Ok(token) => Ok(Json(serde_json::json!({
+            "token": token,
+            "expires_in": state.config.auth.jwt_ttl_hours * 3600
+        }))),
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
     }
 }
 
-async fn verify_handler(
+/// Confirm the caller's own bearer token is still valid. Verification itself now
+/// happens entirely inside the `AuthenticatedUser` extractor, which rejects with
+/// `401` before this body ever runs.
+async fn verify_handler(AuthenticatedUser(claims): AuthenticatedUser) -> Json<serde_json::Value> {
+    Json(serde_json::json!({
+        "valid": true,
+        "claims": claims
+    }))
+}
+
+/// Revoke the presented token so it's rejected by `/verify` even though it hasn't
+/// reached its `exp` yet.
+async fn logout_handler(
     State(state): State<AppState>,
     Json(payload): Json<serde_json::Value>,
 ) -> Result<Json<serde_json::Value>, StatusCode> {
     let token = payload.get("token")
         .and_then(|v| v.as_str())
         .ok_or(StatusCode::BAD_REQUEST)?;
-    
-    match state.jwt_manager.verify_token(token) {
-        Ok(claims) => Ok(Json(serde_json::json!({
-            "valid": true,
-            "claims": claims
-        }))),
-        Err(_) => Ok(Json(serde_json::json!({
-            "valid": false
-        }))),
+
+    let claims = state.jwt_manager.verify_token(token, state.kv_manager.as_deref(), jwt::TokenPurpose::Login, &state.config.auth.token_issuer).await
+        .map_err(|_| StatusCode::UNAUTHORIZED)?;
+
+    let kv_manager = state.kv_manager.as_deref().ok_or(StatusCode::SERVICE_UNAVAILABLE)?;
+    state.jwt_manager.revoke(kv_manager, &claims).await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(serde_json::json!({ "revoked": true })))
+}
+
+/// Lists active session count. Requires the `view_status` permission under the
+/// service's `PermissionPolicy`, enforced via the reusable `RequirePermission`
+/// extractor rather than an inline policy check.
+async fn admin_sessions_handler(
+    State(state): State<AppState>,
+    RequirePermission(..): RequirePermission<ViewStatusPermission>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let sessions = state.session_store.read().await;
+    Ok(Json(serde_json::json!({
+        "active_sessions": sessions.sessions.len()
+    })))
+}
+
+/// Redeem a refresh token for a fresh access+refresh pair, rotating the refresh
+/// token in the process. A refresh token whose stored record is already marked used
+/// is treated as stolen/replayed and revokes the whole session rather than just
+/// failing the call. Once a rotation completes, the stored record is the new token's,
+/// so presenting the old token again fails the hash check with `401` and leaves the
+/// session in place.
+async fn refresh_handler(
+    State(state): State<AppState>,
+    Json(payload): Json<serde_json::Value>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let session_id = payload.get("session_id")
+        .and_then(|v| v.as_str())
+        .ok_or(StatusCode::BAD_REQUEST)?;
+    let refresh_token = payload.get("refresh_token")
+        .and_then(|v| v.as_str())
+        .ok_or(StatusCode::BAD_REQUEST)?;
+
+    // Check-and-mark the refresh token as used under a single write-lock critical
+    // section, so two concurrent presentations of the same token can't both read
+    // `used: false` and both pass verification. The second one through the lock sees
+    // either the first's `used: true` (rejected as a replay, revoking the session) or,
+    // once the rotated record has been stored below, a hash that doesn't match
+    // (rejected with `401`).
+    let identity = {
+        let mut store = state.session_store.write().await;
+        let identity = store.get_session(session_id).cloned().ok_or(StatusCode::UNAUTHORIZED)?;
+        let record = store.get_refresh_token(session_id).cloned().ok_or(StatusCode::UNAUTHORIZED)?;
+
+        if let Err(e) = JwtManager::verify_refresh_token(refresh_token, &record) {
+            if matches!(e, JwtError::Revoked) {
+                tracing::warn!("Refresh token reuse detected for session {}; revoking session", session_id);
+                store.revoke_session(session_id);
+                drop(store);
+                if let Some(ref kv_manager) = state.kv_manager {
+                    let _ = kv_manager.delete_refresh_token(session_id).await;
+                }
+            }
+            return Err(StatusCode::UNAUTHORIZED);
+        }
+
+        let mut used_record = record;
+        used_record.used = true;
+        store.set_refresh_token(session_id.to_string(), used_record);
+
+        identity
+    };
+
+    let (token, new_refresh_token, new_record) = state.jwt_manager.create_token_pair(
+        &identity,
+        state.config.auth.jwt_ttl_hours,
+        false,
+        state.config.auth.refresh_token_size,
+        state.config.auth.refresh_token_expire_days,
+        &state.config.auth.token_issuer,
+    ).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    state.session_store.write().await.set_refresh_token(session_id.to_string(), new_record.clone());
+
+    if let Some(ref kv_manager) = state.kv_manager {
+        if let Err(e) = kv_manager.put_refresh_token(session_id, &new_record).await {
+            tracing::warn!("Failed to persist rotated refresh token to KV store: {}", e);
+        }
     }
+
+    Ok(Json(serde_json::json!({
+        "token": token,
+        "refresh_token": new_refresh_token,
+        "session_id": session_id
+    })))
 }
 
 async fn root_handler() -> &'static str {
     "AuthFace - Multi-website Authentication and Authorization Service"
 }
-
-async fn load_config() -> Result<AppConfig, Box<dyn std::error::Error>> {
-    // For now, return a default configuration
-    // In a real implementation, you'd load from config files or environment variables
-    Ok(AppConfig {
-        server: models::ServerConfig {
-            host: "0.0.0.0".to_string(),
-            port: 8080,
-        },
-        auth: models::AuthConfig {
-            oidc_ttl_days: 7,
-            jwt_ttl_hours: 24,
-        },
-        cloudflare: models::CloudflareConfig {
-            account_id: std::env::var("CLOUDFLARE_ACCOUNT_ID").unwrap_or_default(),
-            namespace_id: std::env::var("CLOUDFLARE_NAMESPACE_ID").unwrap_or_default(),
-            api_token: std::env::var("CLOUDFLARE_API_TOKEN").unwrap_or_default(),
-        },
-        security: models::SecurityConfig {
-            jwt_private_key_path: "/etc/authface/jwt_private_key.pem".to_string(),
-            jwt_public_key_path: "/etc/authface/jwt_public_key.pem".to_string(),
-        },
-        oidc_providers: std::collections::HashMap::new(),
-    })
-}
\ No newline at end of file
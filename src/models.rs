@@ -3,7 +3,7 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
 /// User tier enumeration
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum UserTier {
     Admin,
     Preferred,
@@ -20,6 +20,22 @@ impl UserTier {
             UserTier::Free => "free",
         }
     }
+
+    /// Total order over tiers, highest-privilege first, used to check "at least this
+    /// tier" requirements without hand-rolling a match per comparison site.
+    fn rank(&self) -> u8 {
+        match self {
+            UserTier::Admin => 3,
+            UserTier::Preferred => 2,
+            UserTier::Normal => 1,
+            UserTier::Free => 0,
+        }
+    }
+
+    /// Whether this tier carries at least the privilege of `minimum`.
+    pub fn at_least(&self, minimum: &UserTier) -> bool {
+        self.rank() >= minimum.rank()
+    }
 }
 
 /// OIDC identity information
@@ -32,6 +48,11 @@ pub struct OidcIdentity {
     pub tier: UserTier,       // User tier
     pub created_at: DateTime<Utc>,
     pub expires_at: DateTime<Utc>,
+    /// Permission names (see `permissions::Permission::as_str`) granted to this
+    /// identity on top of whatever its tier carries by default. Absent from older
+    /// serialized sessions, which is treated as no overrides.
+    #[serde(default)]
+    pub permission_overrides: Vec<String>,
 }
 
 /// JWT token claims
@@ -45,6 +66,23 @@ pub struct JwtClaims {
     pub iat: i64,              // Issued at
     pub exp: i64,              // Expiration time
     pub jti: String,           // JWT ID
+    /// Whether the session this token was minted from completed TOTP verification.
+    /// `false` for identities that never enrolled a second factor.
+    #[serde(default)]
+    pub mfa_completed: bool,
+    /// `"{AuthConfig::token_issuer}|{TokenPurpose::as_str()}"`. Scopes the token to the
+    /// purpose it was minted for, so e.g. an email-verification token can't be replayed
+    /// as a login token.
+    pub iss: String,
+    /// The `TokenPurpose` this token was minted for, as a string (mirrors `iss`'s
+    /// suffix so `Validation::set_audience` has something independent to check).
+    pub aud: String,
+    /// Permission names (see `permissions::Permission::as_str`) granted to this
+    /// identity on top of whatever its tier carries by default, carried over from
+    /// `OidcIdentity::permission_overrides` at mint time. Absent from tokens minted
+    /// before this field existed, which is treated as no overrides.
+    #[serde(default)]
+    pub permission_overrides: Vec<String>,
 }
 
 /// OIDC provider configuration
@@ -52,30 +90,116 @@ pub struct JwtClaims {
 pub struct OidcProvider {
     pub client_id: String,
     pub client_secret: String,
+    /// Issuer URL, e.g. `https://accounts.google.com`. The discovery document is
+    /// fetched from `discovery_url`, not derived from this.
+    pub issuer: String,
+    /// Full URL of the discovery document, usually `{issuer}/.well-known/openid-configuration`.
     pub discovery_url: String,
     pub name: String,
 }
 
+/// Provider metadata fetched from the OIDC discovery document
+/// (`{issuer}/.well-known/openid-configuration`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProviderMetadata {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub userinfo_endpoint: Option<String>,
+    pub jwks_uri: String,
+    #[serde(default)]
+    pub device_authorization_endpoint: Option<String>,
+}
+
+/// A local account to provision at startup. Lets an operator seed accounts (e.g. a
+/// break-glass admin) from `AUTHFACE_CONFIG` instead of needing a registration
+/// endpoint, matching how `oidc_providers` is config-driven rather than API-driven.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LocalAccountSeed {
+    pub username: String,
+    pub password: String,
+    pub tier: UserTier,
+}
+
 /// Application configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AppConfig {
     pub server: ServerConfig,
     pub auth: AuthConfig,
     pub cloudflare: CloudflareConfig,
+    pub session_backend: SessionBackendConfig,
     pub security: SecurityConfig,
+    #[serde(default)]
     pub oidc_providers: HashMap<String, OidcProvider>,
+    /// Local accounts to provision on startup. See `LocalAccountSeed`.
+    #[serde(default)]
+    pub local_accounts: Vec<LocalAccountSeed>,
+}
+
+impl Default for AppConfig {
+    /// The configuration used when `AUTHFACE_CONFIG` names no file; `config::load`
+    /// layers environment variables on top of this before validating it.
+    fn default() -> Self {
+        Self {
+            server: ServerConfig::default(),
+            auth: AuthConfig::default(),
+            cloudflare: CloudflareConfig::default(),
+            session_backend: SessionBackendConfig::default(),
+            security: SecurityConfig::default(),
+            oidc_providers: HashMap::new(),
+            local_accounts: Vec::new(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ServerConfig {
     pub host: String,
     pub port: u16,
+    /// This deployment's externally-reachable origin, e.g. `https://auth.example.com`.
+    /// Used to build OIDC redirect URIs instead of hardcoding `localhost`.
+    pub base_url: String,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            host: "0.0.0.0".to_string(),
+            port: 8080,
+            base_url: "http://localhost:8080".to_string(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AuthConfig {
     pub oidc_ttl_days: u32,
     pub jwt_ttl_hours: u32,
+    /// Per-tier permission grants (see `permissions::Permission::as_str`) layered on
+    /// top of the built-in defaults. Keyed by `UserTier::as_str()`.
+    #[serde(default)]
+    pub tier_permission_overrides: HashMap<String, Vec<String>>,
+    /// Byte length of a refresh token's random payload, before base64url encoding.
+    pub refresh_token_size: usize,
+    /// How long a refresh token stays valid before it must be replaced by a fresh login.
+    pub refresh_token_expire_days: i64,
+    /// This deployment's identity in a minted token's `iss` claim, e.g. `authface` or
+    /// a public hostname. Combined with the token's `TokenPurpose` to form
+    /// `iss = "{token_issuer}|{purpose}"`.
+    pub token_issuer: String,
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        Self {
+            oidc_ttl_days: 7,
+            jwt_ttl_hours: 24,
+            tier_permission_overrides: HashMap::new(),
+            refresh_token_size: 32,
+            refresh_token_expire_days: 30,
+            token_issuer: "authface".to_string(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -85,16 +209,70 @@ pub struct CloudflareConfig {
     pub api_token: String,
 }
 
+impl Default for CloudflareConfig {
+    /// Empty by default; `config::load` overlays the `CLOUDFLARE_*` environment
+    /// variables on top, as it always has.
+    fn default() -> Self {
+        Self {
+            account_id: String::new(),
+            namespace_id: String::new(),
+            api_token: String::new(),
+        }
+    }
+}
+
+/// Which `session_backend::SessionBackend` implementation stores `SessionStore`'s
+/// identities.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum SessionBackendConfig {
+    /// Use `CloudflareKvManager`, built from `AppConfig::cloudflare`.
+    Cloudflare,
+    Redis { url: String, pool_size: u32 },
+}
+
+impl Default for SessionBackendConfig {
+    fn default() -> Self {
+        SessionBackendConfig::Cloudflare
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SecurityConfig {
     pub jwt_private_key_path: String,
     pub jwt_public_key_path: String,
 }
 
+impl Default for SecurityConfig {
+    fn default() -> Self {
+        Self {
+            jwt_private_key_path: "/etc/authface/jwt_private_key.pem".to_string(),
+            jwt_public_key_path: "/etc/authface/jwt_public_key.pem".to_string(),
+        }
+    }
+}
+
+/// A refresh token's server-side record. The raw token is never stored, only a hash
+/// of it, so a leaked database/KV snapshot doesn't hand out usable tokens.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RefreshTokenRecord {
+    pub sub: String,
+    pub token_hash: String,
+    pub expires_at: DateTime<Utc>,
+    /// Set once this token has been redeemed by `/refresh`. Presenting a token whose
+    /// record is still marked used is a reuse/replay signal and revokes the whole
+    /// session. `/refresh` replaces the used record with the rotated one, so a token
+    /// presented after its rotation has completed no longer has a record and fails
+    /// the hash check instead, without revoking the session.
+    pub used: bool,
+}
+
 /// In-memory storage for active sessions
 #[derive(Debug, Clone)]
 pub struct SessionStore {
     pub sessions: HashMap<String, OidcIdentity>,
+    /// Refresh token record per session, keyed by `session_id`.
+    pub refresh_tokens: HashMap<String, RefreshTokenRecord>,
     pub last_cleanup: DateTime<Utc>,
 }
 
@@ -102,6 +280,7 @@ impl SessionStore {
     pub fn new() -> Self {
         Self {
             sessions: HashMap::new(),
+            refresh_tokens: HashMap::new(),
             last_cleanup: Utc::now(),
         }
     }
@@ -120,14 +299,33 @@ impl SessionStore {
     pub fn cleanup_expired(&mut self) -> usize {
         let now = Utc::now();
         let initial_count = self.sessions.len();
-        
+
         self.sessions.retain(|_, identity| identity.expires_at > now);
-        
+        self.refresh_tokens.retain(|session_id, _| self.sessions.contains_key(session_id));
+
         let removed_count = initial_count - self.sessions.len();
         self.last_cleanup = now;
         removed_count
     }
 
+    /// Record `record` as the current refresh token for `session_id`, replacing
+    /// whatever was stored previously (e.g. after rotation).
+    pub fn set_refresh_token(&mut self, session_id: String, record: RefreshTokenRecord) {
+        self.refresh_tokens.insert(session_id, record);
+    }
+
+    /// Look up the refresh-token record for `session_id`.
+    pub fn get_refresh_token(&self, session_id: &str) -> Option<&RefreshTokenRecord> {
+        self.refresh_tokens.get(session_id)
+    }
+
+    /// Remove a session and its refresh token entirely, e.g. on detected
+    /// refresh-token reuse.
+    pub fn revoke_session(&mut self, session_id: &str) {
+        self.sessions.remove(session_id);
+        self.refresh_tokens.remove(session_id);
+    }
+
     /// Get all active sessions for serialization
     pub fn get_all_sessions(&self) -> &HashMap<String, OidcIdentity> {
         &self.sessions
@@ -138,4 +336,9 @@ impl SessionStore {
         self.sessions = sessions;
         self.last_cleanup = Utc::now();
     }
+
+    /// Load refresh tokens from serialized data, mirroring `load_sessions`.
+    pub fn load_refresh_tokens(&mut self, refresh_tokens: HashMap<String, RefreshTokenRecord>) {
+        self.refresh_tokens = refresh_tokens;
+    }
 }
\ No newline at end of file
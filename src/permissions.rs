@@ -0,0 +1,182 @@
+use crate::models::{AuthConfig, OidcIdentity, UserTier};
+
+/// A single grantable capability. New permissions should be added here and given a
+/// default tier in `PermissionPolicy::default_policy`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Permission {
+    ViewStatus,
+    ManageSessions,
+    RevokeTokens,
+    ManageAccounts,
+}
+
+impl Permission {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Permission::ViewStatus => "view_status",
+            Permission::ManageSessions => "manage_sessions",
+            Permission::RevokeTokens => "revoke_tokens",
+            Permission::ManageAccounts => "manage_accounts",
+        }
+    }
+
+    fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "view_status" => Some(Permission::ViewStatus),
+            "manage_sessions" => Some(Permission::ManageSessions),
+            "revoke_tokens" => Some(Permission::RevokeTokens),
+            "manage_accounts" => Some(Permission::ManageAccounts),
+            _ => None,
+        }
+    }
+
+    /// Whether exercising this permission requires the caller's token to carry
+    /// `mfa_completed = true`, on top of whatever tier/override check already passed.
+    /// Permissions that can revoke access or mutate accounts are sensitive enough to
+    /// gate behind a completed second factor; read-only or self-service ones aren't.
+    pub fn requires_mfa(&self) -> bool {
+        matches!(self, Permission::RevokeTokens | Permission::ManageAccounts)
+    }
+}
+
+/// Maps each `UserTier` to the set of permissions it carries by default. Built from
+/// hardcoded defaults plus whatever `AuthConfig::tier_permission_overrides` adds on
+/// top, so an operator can grant e.g. `manage_sessions` to `Normal` without a
+/// code change.
+#[derive(Debug, Clone)]
+pub struct PermissionPolicy {
+    granted: std::collections::HashMap<UserTier, Vec<Permission>>,
+}
+
+impl PermissionPolicy {
+    /// The permissions every tier has before any config overrides are layered in.
+    fn default_policy() -> std::collections::HashMap<UserTier, Vec<Permission>> {
+        let mut granted = std::collections::HashMap::new();
+        granted.insert(
+            UserTier::Admin,
+            vec![
+                Permission::ViewStatus,
+                Permission::ManageSessions,
+                Permission::RevokeTokens,
+                Permission::ManageAccounts,
+            ],
+        );
+        granted.insert(
+            UserTier::Preferred,
+            vec![Permission::ViewStatus, Permission::ManageSessions],
+        );
+        granted.insert(UserTier::Normal, vec![Permission::ViewStatus]);
+        granted.insert(UserTier::Free, vec![]);
+        granted
+    }
+
+    /// Build the policy from `AuthConfig`, layering `tier_permission_overrides` on top
+    /// of `default_policy`. Unknown permission names are ignored rather than rejected,
+    /// since a stale config shouldn't stop the service from starting.
+    pub fn from_config(config: &AuthConfig) -> Self {
+        let mut granted = Self::default_policy();
+
+        for (tier_name, permission_names) in &config.tier_permission_overrides {
+            let Some(tier) = UserTier::from_str(tier_name) else {
+                continue;
+            };
+            let entry = granted.entry(tier).or_default();
+            for name in permission_names {
+                if let Some(permission) = Permission::from_str(name) {
+                    if !entry.contains(&permission) {
+                        entry.push(permission);
+                    }
+                }
+            }
+        }
+
+        Self { granted }
+    }
+
+    /// Permissions granted to `tier` by this policy, not including any per-identity
+    /// `permission_overrides`.
+    pub fn permissions_for(&self, tier: &UserTier) -> &[Permission] {
+        self.granted.get(tier).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    /// Whether `tier` carries `permission` under this policy, not counting any
+    /// per-identity `permission_overrides`. Used by callers that have a `UserTier`
+    /// rather than an `OidcIdentity` on hand (e.g. from `JwtClaims`, whose
+    /// `permission_overrides` they check separately).
+    pub fn tier_has_permission(&self, tier: &UserTier, permission: Permission) -> bool {
+        self.permissions_for(tier).contains(&permission)
+    }
+}
+
+impl UserTier {
+    fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "admin" => Some(UserTier::Admin),
+            "preferred" => Some(UserTier::Preferred),
+            "normal" => Some(UserTier::Normal),
+            "free" => Some(UserTier::Free),
+            _ => None,
+        }
+    }
+}
+
+impl OidcIdentity {
+    /// Whether this identity may exercise `permission`, either because its tier
+    /// carries it under `policy` or because it was granted directly via
+    /// `permission_overrides`.
+    pub fn has_permission(&self, policy: &PermissionPolicy, permission: Permission) -> bool {
+        policy.permissions_for(&self.tier).contains(&permission)
+            || self
+                .permission_overrides
+                .iter()
+                .any(|p| p.as_str() == permission.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, Utc};
+
+    fn identity_with(tier: UserTier, overrides: Vec<String>) -> OidcIdentity {
+        OidcIdentity {
+            sub: "test".to_string(),
+            name: None,
+            email: None,
+            provider: "google".to_string(),
+            tier,
+            created_at: Utc::now(),
+            expires_at: Utc::now() + Duration::days(7),
+            permission_overrides: overrides,
+        }
+    }
+
+    #[test]
+    fn admin_has_manage_accounts_by_default() {
+        let policy = PermissionPolicy::default_policy_for_test();
+        let identity = identity_with(UserTier::Admin, vec![]);
+        assert!(identity.has_permission(&policy, Permission::ManageAccounts));
+    }
+
+    #[test]
+    fn free_tier_gains_permission_via_override() {
+        let policy = PermissionPolicy::default_policy_for_test();
+        let identity = identity_with(UserTier::Free, vec!["view_status".to_string()]);
+        assert!(identity.has_permission(&policy, Permission::ViewStatus));
+    }
+
+    #[test]
+    fn normal_tier_lacks_revoke_tokens() {
+        let policy = PermissionPolicy::default_policy_for_test();
+        let identity = identity_with(UserTier::Normal, vec![]);
+        assert!(!identity.has_permission(&policy, Permission::RevokeTokens));
+    }
+
+    impl PermissionPolicy {
+        fn default_policy_for_test() -> Self {
+            Self {
+                granted: Self::default_policy(),
+            }
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use crate::models::OidcIdentity;
+use crate::session_backend::{SessionBackend, SessionBackendError};
+use async_trait::async_trait;
+use deadpool_redis::redis::AsyncCommands;
+use deadpool_redis::{Config as RedisConfig, Pool, Runtime};
+use std::collections::HashMap;
+
+const SESSION_KEY_PREFIX: &str = "authface:session:";
+
+/// Redis-backed `SessionBackend`, for deployments that want low-latency session
+/// storage shared across multiple AuthFace instances instead of Cloudflare's edge KV.
+pub struct RedisSessionBackend {
+    pool: Pool,
+}
+
+impl std::fmt::Debug for RedisSessionBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RedisSessionBackend").finish()
+    }
+}
+
+impl RedisSessionBackend {
+    pub fn new(redis_url: &str, pool_size: usize) -> Result<Self, SessionBackendError> {
+        let mut config = RedisConfig::from_url(redis_url);
+        config.pool = Some(deadpool_redis::PoolConfig::new(pool_size));
+        let pool = config
+            .create_pool(Some(Runtime::Tokio1))
+            .map_err(|e| SessionBackendError::Redis(e.to_string()))?;
+
+        Ok(Self { pool })
+    }
+
+    async fn connection(&self) -> Result<deadpool_redis::Connection, SessionBackendError> {
+        self.pool.get().await.map_err(|e| SessionBackendError::Redis(e.to_string()))
+    }
+}
+
+#[async_trait]
+impl SessionBackend for RedisSessionBackend {
+    /// Page through session keys with cursor-based `SCAN` rather than `KEYS`, which
+    /// blocks the whole Redis instance for the duration of the scan — exactly what a
+    /// low-latency, multi-instance deployment can't afford.
+    async fn load_all(&self) -> Result<HashMap<String, OidcIdentity>, SessionBackendError> {
+        let mut conn = self.connection().await?;
+        let pattern = format!("{}*", SESSION_KEY_PREFIX);
+        let mut sessions = HashMap::new();
+        let mut cursor: u64 = 0;
+
+        loop {
+            let (next_cursor, keys): (u64, Vec<String>) = deadpool_redis::redis::cmd("SCAN")
+                .arg(cursor)
+                .arg("MATCH")
+                .arg(&pattern)
+                .arg("COUNT")
+                .arg(200)
+                .query_async(&mut conn)
+                .await
+                .map_err(|e| SessionBackendError::Redis(e.to_string()))?;
+
+            for key in keys {
+                let value: Option<String> = conn
+                    .get(&key)
+                    .await
+                    .map_err(|e| SessionBackendError::Redis(e.to_string()))?;
+                let Some(value) = value else { continue };
+
+                let session_id = key.trim_start_matches(SESSION_KEY_PREFIX).to_string();
+                match serde_json::from_str::<OidcIdentity>(&value) {
+                    Ok(identity) => {
+                        sessions.insert(session_id, identity);
+                    }
+                    Err(e) => tracing::warn!("Dropping unparseable Redis session {}: {}", key, e),
+                }
+            }
+
+            if next_cursor == 0 {
+                break;
+            }
+            cursor = next_cursor;
+        }
+
+        tracing::info!("Loaded {} sessions from Redis", sessions.len());
+        Ok(sessions)
+    }
+
+    async fn put(&self, session_id: &str, identity: &OidcIdentity) -> Result<(), SessionBackendError> {
+        let mut conn = self.connection().await?;
+        let value = serde_json::to_string(identity).map_err(|e| SessionBackendError::Redis(e.to_string()))?;
+        let ttl_seconds = (identity.expires_at - chrono::Utc::now()).num_seconds().max(1) as u64;
+
+        conn.set_ex::<_, _, ()>(format!("{}{}", SESSION_KEY_PREFIX, session_id), value, ttl_seconds)
+            .await
+            .map_err(|e| SessionBackendError::Redis(e.to_string()))
+    }
+
+    async fn delete(&self, session_id: &str) -> Result<(), SessionBackendError> {
+        let mut conn = self.connection().await?;
+        conn.del::<_, ()>(format!("{}{}", SESSION_KEY_PREFIX, session_id))
+            .await
+            .map_err(|e| SessionBackendError::Redis(e.to_string()))
+    }
+
+    async fn cleanup_expired(&self, _keep_days: u32) -> Result<(), SessionBackendError> {
+        // Redis's own per-key TTL (set via `put`'s SET EX) is authoritative here;
+        // unlike Cloudflare KV there's no history of keys written without one to sweep.
+        Ok(())
+    }
+}
@@ -0,0 +1,32 @@
+use crate::models::OidcIdentity;
+use async_trait::async_trait;
+use std::collections::HashMap;
+
+#[derive(Debug, thiserror::Error)]
+pub enum SessionBackendError {
+    #[error("Cloudflare KV error: {0}")]
+    Cloudflare(#[from] crate::cloudflare::CloudflareError),
+    #[error("Redis error: {0}")]
+    Redis(String),
+}
+
+/// Storage for `SessionStore`'s identities, abstracted over the concrete store so the
+/// service isn't locked into Cloudflare's edge KV. The hourly cleanup task and the
+/// startup load both go through this trait rather than a concrete backend type.
+#[async_trait]
+pub trait SessionBackend: Send + Sync + std::fmt::Debug {
+    /// Load every persisted session, keyed by session ID.
+    async fn load_all(&self) -> Result<HashMap<String, OidcIdentity>, SessionBackendError>;
+
+    /// Persist (or overwrite) a single session, expiring it at `identity.expires_at`,
+    /// or later where that is closer than the backend's minimum TTL.
+    async fn put(&self, session_id: &str, identity: &OidcIdentity) -> Result<(), SessionBackendError>;
+
+    /// Remove a single session.
+    async fn delete(&self, session_id: &str) -> Result<(), SessionBackendError>;
+
+    /// Sweep any sessions the backend didn't already expire on its own. Backends with
+    /// native per-key TTLs (Cloudflare KV, Redis) treat this as a safety net rather
+    /// than the primary expiry mechanism.
+    async fn cleanup_expired(&self, keep_days: u32) -> Result<(), SessionBackendError>;
+}